@@ -1,19 +1,27 @@
-use std::{env, io::Write, time::Duration};
+use std::{
+    env,
+    error::Error as _,
+    io::{Read, Write},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use chrono::{DateTime, Datelike, FixedOffset, NaiveDateTime, NaiveTime, Timelike};
+use cidr::{IpCidr, IpInet};
 use nu_plugin::{
     serve_plugin, EngineInterface, EvaluatedCall, MsgPackSerializer, PluginCommand,
     SimplePluginCommand,
 };
 use nu_protocol::{LabeledError, Record, ShellError, Signature, Span, SyntaxShape, Value};
-use pg_query::NodeEnum;
+use pg_query::{Node, NodeEnum};
 use postgres::{
     fallible_iterator::FallibleIterator,
     types::{FromSql, Oid, ToSql, Type},
     Client,
 };
+use rust_decimal::Decimal;
 use rustls::RootCertStore;
 use tokio_postgres_rustls::MakeRustlsConnect;
+use uuid::Uuid;
 
 fn main() {
     serve_plugin(&PgPlugin::new(), MsgPackSerializer);
@@ -50,6 +58,24 @@ impl SimplePluginCommand for PgCommand {
         Signature::new("pg")
             .add_help()
             .required("query", SyntaxShape::String, "query to execute")
+            .named(
+                "params",
+                SyntaxShape::Any,
+                "values to bind to `$1`.. placeholders in the query, as a list or record",
+                None,
+            )
+            .named(
+                "connect-retries",
+                SyntaxShape::Int,
+                "number of times to retry a transient connection failure (default: 5)",
+                None,
+            )
+            .named(
+                "connect-max-elapsed",
+                SyntaxShape::Duration,
+                "maximum total time to spend retrying the connection (default: 30sec, or $PG_CONNECT_MAX_ELAPSED)",
+                None,
+            )
     }
 
     fn usage(&self) -> &str {
@@ -63,7 +89,7 @@ impl SimplePluginCommand for PgCommand {
         call: &EvaluatedCall,
         input: &Value,
     ) -> Result<Value, LabeledError> {
-        let args = Args::parse(call)?;
+        let args = Args::parse(call, engine)?;
         let mut config = load_config(engine)?;
 
         let input = match input {
@@ -80,7 +106,8 @@ impl SimplePluginCommand for PgCommand {
 
         config.connect_timeout(Duration::from_secs(30));
 
-        let mut client = config.connect(tls_connector()).map_err(from_pg_error)?;
+        let mut client =
+            connect_with_retry(&config, args.connect_retries, args.connect_max_elapsed)?;
 
         let mut output_values = Vec::new();
 
@@ -96,17 +123,28 @@ impl SimplePluginCommand for PgCommand {
 
             match node {
                 NodeEnum::SelectStmt(_) => {
-                    let value = execute_query(&mut client, &query)?;
+                    let params = bind_params(stmt, &args.params)?;
+                    let value = execute_query(&mut client, &query, &params)?;
 
                     output_values.push(value);
                 }
                 NodeEnum::CopyStmt(stmt) => {
                     if !stmt.is_from && !stmt.is_program {
-                        return Err(LabeledError::new("`COPY … TO STDOUT` is not supported"));
+                        let mut reader = client
+                            .copy_out(&query)
+                            .map_err(|err| LabeledError::new(err.to_string()))?;
+
+                        let mut buf = Vec::new();
+                        reader
+                            .read_to_end(&mut buf)
+                            .map_err(|err| LabeledError::new(err.to_string()))?;
+
+                        output_values.push(Value::binary(buf, Span::unknown()));
+                        continue;
                     }
 
                     if !stmt.is_from || stmt.is_program {
-                        execute_query(&mut client, &query)?;
+                        execute_query(&mut client, &query, &[])?;
                         continue;
                     }
 
@@ -123,7 +161,8 @@ impl SimplePluginCommand for PgCommand {
                         .map_err(|err| LabeledError::new(err.to_string()))?;
                 }
                 _ => {
-                    execute_query(&mut client, &query)?;
+                    let params = bind_params(stmt, &args.params)?;
+                    execute_query(&mut client, &query, &params)?;
                 }
             }
         }
@@ -138,8 +177,12 @@ impl SimplePluginCommand for PgCommand {
     }
 }
 
-fn execute_query(client: &mut Client, query: &str) -> Result<Value, LabeledError> {
-    let params: [&dyn ToSql; 0] = [];
+fn execute_query(
+    client: &mut Client,
+    query: &str,
+    params: &[Box<dyn ToSql + Sync>],
+) -> Result<Value, LabeledError> {
+    let params = params.iter().map(|param| param.as_ref() as &dyn ToSql);
     let mut rows = client.query_raw(query, params).map_err(from_pg_error)?;
 
     let mut nu_rows = Vec::new();
@@ -190,6 +233,52 @@ fn execute_query(client: &mut Client, query: &str) -> Result<Value, LabeledError
                     Value::record(time, span)
                 }),
                 Type::OID => row_get_opt(row, i, |value: Oid| Value::int(value.into(), span)),
+                // `try_get`, not `row_get_opt`: Postgres `NUMERIC` allows
+                // `NaN` and more significant digits than `Decimal` can
+                // represent, so decoding a valid column can fail and must
+                // surface as an error rather than panic.
+                Type::NUMERIC => row_try_get::<Decimal>(row, col, i)?
+                    .map(|value| Value::string(value.to_string(), span))
+                    .unwrap_or_else(|| Value::nothing(span)),
+                Type::UUID => {
+                    row_get_opt(row, i, |value: Uuid| Value::string(value.to_string(), span))
+                }
+                Type::BYTEA => row_get_opt(row, i, |value: Vec<u8>| Value::binary(value, span)),
+                Type::INET => row_get_opt(row, i, |value: IpInet| {
+                    Value::string(value.to_string(), span)
+                }),
+                Type::CIDR => row_get_opt(row, i, |value: IpCidr| {
+                    Value::string(value.to_string(), span)
+                }),
+                Type::BOOL_ARRAY => row_get_opt(row, i, |value: Vec<Option<bool>>| {
+                    array_to_nu(value, span, |value| Value::bool(value, span))
+                }),
+                Type::INT2_ARRAY => row_get_opt(row, i, |value: Vec<Option<i16>>| {
+                    array_to_nu(value, span, |value| Value::int(value.into(), span))
+                }),
+                Type::INT4_ARRAY => row_get_opt(row, i, |value: Vec<Option<i32>>| {
+                    array_to_nu(value, span, |value| Value::int(value.into(), span))
+                }),
+                Type::INT8_ARRAY => row_get_opt(row, i, |value: Vec<Option<i64>>| {
+                    array_to_nu(value, span, |value| Value::int(value, span))
+                }),
+                Type::FLOAT4_ARRAY => row_get_opt(row, i, |value: Vec<Option<f32>>| {
+                    array_to_nu(value, span, |value| Value::float(value.into(), span))
+                }),
+                Type::FLOAT8_ARRAY => row_get_opt(row, i, |value: Vec<Option<f64>>| {
+                    array_to_nu(value, span, |value| Value::float(value, span))
+                }),
+                Type::TEXT_ARRAY | Type::VARCHAR_ARRAY => {
+                    row_get_opt(row, i, |value: Vec<Option<String>>| {
+                        array_to_nu(value, span, |value| Value::string(value, span))
+                    })
+                }
+                Type::UUID_ARRAY => row_get_opt(row, i, |value: Vec<Option<Uuid>>| {
+                    array_to_nu(value, span, |value| Value::string(value.to_string(), span))
+                }),
+                Type::BYTEA_ARRAY => row_get_opt(row, i, |value: Vec<Option<Vec<u8>>>| {
+                    array_to_nu(value, span, |value| Value::binary(value, span))
+                }),
                 ref r#type => {
                     return Err(LabeledError::new(format!(
                         "column `{}` has unsupported type `{type}`",
@@ -217,14 +306,136 @@ fn row_get_opt<'a, T: FromSql<'a>>(
         .unwrap_or_else(|| Value::nothing(Span::unknown()))
 }
 
+/// Like `row_get_opt`, but for conversions that can fail on an otherwise
+/// valid column value (e.g. a `NUMERIC` too large or `NaN` for `Decimal`) —
+/// returns a `LabeledError` instead of panicking.
+fn row_try_get<'a, T: FromSql<'a>>(
+    row: &'a postgres::Row,
+    col: &postgres::Column,
+    i: usize,
+) -> Result<Option<T>, LabeledError> {
+    row.try_get(i).map_err(|err| {
+        LabeledError::new(format!(
+            "column `{}` has a value that could not be decoded: {err}",
+            col.name(),
+        ))
+    })
+}
+
+/// Converts a Postgres array, decoded as `Vec<Option<T>>`, into a Nu list,
+/// recursing `to_value` over every non-null element.
+fn array_to_nu<T>(values: Vec<Option<T>>, span: Span, to_value: impl Fn(T) -> Value) -> Value {
+    let values = values
+        .into_iter()
+        .map(|value| value.map(&to_value).unwrap_or_else(|| Value::nothing(span)))
+        .collect();
+
+    Value::list(values, span)
+}
+
 fn from_pg_error(err: postgres::Error) -> LabeledError {
     let Some(db_err) = err.as_db_error() else {
         return LabeledError::new(err.to_string());
     };
 
+    let code = db_err.code().code();
     let msg = db_err.to_string();
+    let error = LabeledError::new(msg).with_code(code);
+
+    let mut help = Vec::new();
+
+    if let Some(name) = sqlstate_name(code) {
+        help.push(format!("condition: {name}"));
+    } else if let Some(class) = sqlstate_class(code) {
+        help.push(format!("class: {class}"));
+    }
+
+    if let Some(detail) = db_err.detail() {
+        help.push(format!("detail: {detail}"));
+    }
+
+    if let Some(hint) = db_err.hint() {
+        help.push(format!("hint: {hint}"));
+    }
+
+    if let Some(column) = db_err.column() {
+        help.push(format!("column: {column}"));
+    }
+
+    if let Some(constraint) = db_err.constraint() {
+        help.push(format!("constraint: {constraint}"));
+    }
+
+    if help.is_empty() {
+        error
+    } else {
+        error.with_help(help.join("\n"))
+    }
+}
 
-    LabeledError::new(msg).with_code(db_err.code().code())
+/// Maps well-known full SQLSTATE codes to a precise, human-readable name.
+fn sqlstate_name(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "23502" => "not_null_violation",
+        "23503" => "foreign_key_violation",
+        "23505" => "unique_violation",
+        "23514" => "check_violation",
+        "28P01" => "invalid_password",
+        "40P01" => "deadlock_detected",
+        "42703" => "undefined_column",
+        "42P01" => "undefined_table",
+        _ => return None,
+    })
+}
+
+/// Decodes the SQLSTATE class (the first two characters of the code) into
+/// its documented category, per the Postgres errcodes appendix.
+fn sqlstate_class(code: &str) -> Option<&'static str> {
+    Some(match code.get(..2)? {
+        "00" => "successful_completion",
+        "01" => "warning",
+        "02" => "no_data",
+        "03" => "sql_statement_not_yet_complete",
+        "08" => "connection_exception",
+        "09" => "triggered_action_exception",
+        "0A" => "feature_not_supported",
+        "0B" => "invalid_transaction_initiation",
+        "0F" => "locator_exception",
+        "0L" => "invalid_grantor",
+        "0P" => "invalid_role_specification",
+        "20" => "case_not_found",
+        "21" => "cardinality_violation",
+        "22" => "data_exception",
+        "23" => "integrity_constraint_violation",
+        "24" => "invalid_cursor_state",
+        "25" => "invalid_transaction_state",
+        "26" => "invalid_sql_statement_name",
+        "27" => "triggered_data_change_violation",
+        "28" => "invalid_authorization_specification",
+        "2B" => "dependent_privilege_descriptors_still_exist",
+        "2D" => "invalid_transaction_termination",
+        "2F" => "sql_routine_exception",
+        "34" => "invalid_cursor_name",
+        "38" => "external_routine_exception",
+        "39" => "external_routine_invocation_exception",
+        "3B" => "savepoint_exception",
+        "3D" => "invalid_catalog_name",
+        "3F" => "invalid_schema_name",
+        "40" => "transaction_rollback",
+        "42" => "syntax_error_or_access_rule_violation",
+        "44" => "with_check_option_violation",
+        "53" => "insufficient_resources",
+        "54" => "program_limit_exceeded",
+        "55" => "object_not_in_prerequisite_state",
+        "57" => "operator_intervention",
+        "58" => "system_error",
+        "72" => "snapshot_too_old",
+        "F0" => "config_file_error",
+        "HV" => "fdw_error",
+        "P0" => "plpgsql_error",
+        "XX" => "internal_error",
+        _ => return None,
+    })
 }
 
 fn tls_connector() -> MakeRustlsConnect {
@@ -242,13 +453,191 @@ fn tls_connector() -> MakeRustlsConnect {
 
 struct Args {
     query: String,
+    params: Option<Value>,
+    connect_retries: u32,
+    connect_max_elapsed: Duration,
 }
 
 impl Args {
-    fn parse(call: &EvaluatedCall) -> Result<Self, ShellError> {
+    fn parse(call: &EvaluatedCall, engine: &EngineInterface) -> Result<Self, ShellError> {
         let query = call.req::<String>(0)?;
+        let params = call.get_flag_value("params");
+        let connect_retries = call.get_flag::<i64>("connect-retries")?.unwrap_or(5).max(0) as u32;
+        let connect_max_elapsed = connect_max_elapsed(call, engine)?;
+
+        Ok(Self {
+            query,
+            params,
+            connect_retries,
+            connect_max_elapsed,
+        })
+    }
+}
+
+const DEFAULT_CONNECT_MAX_ELAPSED: Duration = Duration::from_secs(30);
 
-        Ok(Self { query })
+fn connect_max_elapsed(
+    call: &EvaluatedCall,
+    engine: &EngineInterface,
+) -> Result<Duration, ShellError> {
+    if let Some(nanos) = call.get_flag::<i64>("connect-max-elapsed")? {
+        return Ok(Duration::from_nanos(nanos.max(0) as u64));
+    }
+
+    if let Some(value) = env_var_opt("PG_CONNECT_MAX_ELAPSED", engine)? {
+        if let Ok(secs) = value.parse::<u64>() {
+            return Ok(Duration::from_secs(secs));
+        }
+    }
+
+    Ok(DEFAULT_CONNECT_MAX_ELAPSED)
+}
+
+/// Connects to Postgres, retrying with exponential backoff (plus jitter)
+/// while the failure looks transient (connection refused/reset/aborted) and
+/// we're still within `max_elapsed`. Permanent errors (bad auth, bad config,
+/// …) are returned immediately.
+fn connect_with_retry(
+    config: &postgres::Config,
+    max_retries: u32,
+    max_elapsed: Duration,
+) -> Result<Client, LabeledError> {
+    const INITIAL_DELAY: Duration = Duration::from_millis(100);
+    const MULTIPLIER: f64 = 2.0;
+
+    let start = Instant::now();
+    let mut delay = INITIAL_DELAY;
+    let mut attempt = 0;
+
+    loop {
+        match config.connect(tls_connector()) {
+            Ok(client) => return Ok(client),
+            Err(err) => {
+                let elapsed = start.elapsed();
+
+                if attempt >= max_retries || elapsed >= max_elapsed || !is_transient(&err) {
+                    return Err(from_pg_error(err));
+                }
+
+                std::thread::sleep(delay.min(max_elapsed - elapsed) + jitter(delay));
+                delay = delay.mul_f64(MULTIPLIER);
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// Only connection-refused/reset/aborted I/O errors are considered transient;
+/// everything else (auth failure, bad config, DB errors) is permanent.
+fn is_transient(err: &postgres::Error) -> bool {
+    let mut source = err.source();
+
+    while let Some(err) = source {
+        if let Some(io_err) = err.downcast_ref::<std::io::Error>() {
+            return matches!(
+                io_err.kind(),
+                std::io::ErrorKind::ConnectionRefused
+                    | std::io::ErrorKind::ConnectionReset
+                    | std::io::ErrorKind::ConnectionAborted
+            );
+        }
+
+        source = err.source();
+    }
+
+    false
+}
+
+fn jitter(delay: Duration) -> Duration {
+    let subsec_nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.subsec_nanos())
+        .unwrap_or(0);
+
+    delay.mul_f64(subsec_nanos as f64 / u32::MAX as f64 * 0.5)
+}
+
+/// Flattens the `--params` value (a list or record) into individual values, in
+/// the order they should be bound to `$1..$n`, and validates the count
+/// against the highest placeholder referenced by `stmt`.
+fn bind_params(
+    stmt: &Node,
+    params: &Option<Value>,
+) -> Result<Vec<Box<dyn ToSql + Sync>>, LabeledError> {
+    let values = match params {
+        None => Vec::new(),
+        Some(Value::List { vals, .. }) => vals.clone(),
+        Some(Value::Record { val, .. }) => val.iter().map(|(_, value)| value.clone()).collect(),
+        Some(value) => {
+            return Err(LabeledError::new(format!(
+                "expected `--params` to be a `list` or `record`, but got `{}`",
+                value.get_type(),
+            ))
+            .with_label("invalid `--params` value", value.span()))
+        }
+    };
+
+    let expected = max_param_index(stmt);
+
+    if values.len() != expected {
+        let span = params
+            .as_ref()
+            .map(|value| value.span())
+            .unwrap_or(Span::unknown());
+
+        let msg = if expected == 0 {
+            format!(
+                "query has no `$n` placeholders but {} parameter(s) were supplied",
+                values.len(),
+            )
+        } else {
+            format!(
+                "query references `${expected}`, but {} parameter(s) were supplied",
+                values.len(),
+            )
+        };
+
+        return Err(
+            LabeledError::new(msg).with_label(format!("expected {expected} parameter(s)"), span)
+        );
+    }
+
+    values.iter().map(value_to_sql).collect()
+}
+
+/// Walks the parse tree rooted at `node` and returns the highest `$n`
+/// placeholder index referenced anywhere in it (`0` if there are none).
+fn max_param_index(node: &Node) -> usize {
+    let mut max = 0;
+
+    if let Some(NodeEnum::ParamRef(param_ref)) = &node.node {
+        max = max.max(param_ref.number as usize);
+    }
+
+    for (child, _depth, _context) in node.nodes() {
+        if let Some(NodeEnum::ParamRef(param_ref)) = &child.node {
+            max = max.max(param_ref.number as usize);
+        }
+    }
+
+    max
+}
+
+/// Converts a single bound Nu `Value` into an owned, boxed `ToSql`.
+fn value_to_sql(value: &Value) -> Result<Box<dyn ToSql + Sync>, LabeledError> {
+    match value {
+        Value::Int { val, .. } => Ok(Box::new(*val)),
+        Value::Float { val, .. } => Ok(Box::new(*val)),
+        Value::Bool { val, .. } => Ok(Box::new(*val)),
+        Value::String { val, .. } => Ok(Box::new(val.clone())),
+        Value::Binary { val, .. } => Ok(Box::new(val.clone())),
+        Value::Date { val, .. } => Ok(Box::new(*val)),
+        Value::Nothing { .. } => Ok(Box::new(Option::<&str>::None)),
+        _ => Err(LabeledError::new(format!(
+            "unsupported `--params` value type `{}`",
+            value.get_type(),
+        ))
+        .with_label("cannot bind this value to a query parameter", value.span())),
     }
 }
 